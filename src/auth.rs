@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+use std::future::{ready, Ready};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpResponse};
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use futures::future::LocalBoxFuture;
+use rand::RngCore;
+use serde::Deserialize;
+use sqlx::Row;
+
+use crate::AppState;
+
+/// Cookie carrying the opaque session token issued by `login`.
+pub const SESSION_COOKIE: &str = "coach_session";
+
+/// How long an issued session token stays valid after login.
+const SESSION_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// A fixed, valid argon2id hash with no matching password, verified against
+/// unknown usernames so `login` takes the same time whether or not the
+/// account exists — otherwise the early return on a missing user is a
+/// username-enumeration timing oracle.
+const DUMMY_PASSWORD_HASH: &str =
+    "$argon2id$v=19$m=19456,t=2,p=1$eh5kAe7r2T2erfp327fkjQ$hIQg03klhMXZC4SMj1OXsAkSaz6Gsc+ZjxTNRXeyx/4";
+
+/// How often the background reaper sweeps `Sessions` for expired entries.
+const REAP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// A logged-in username plus when its session token was issued, so
+/// `AuthGateMiddleware` can evict stale entries instead of trusting tokens
+/// forever.
+struct Session {
+    username: String,
+    issued_at: Instant,
+}
+
+/// In-memory session store mapping opaque tokens to their session.
+pub type Sessions = Arc<Mutex<HashMap<String, Session>>>;
+
+#[derive(Deserialize)]
+pub struct Credentials {
+    username: String,
+    password: String,
+}
+
+/// Hashes a password with argon2 using a freshly generated salt.
+fn hash_password(password: &str) -> String {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("Failed to hash password")
+        .to_string()
+}
+
+/// Verifies a password against a stored argon2 hash in constant time.
+fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(parsed_hash) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed_hash)
+            .is_ok(),
+        Err(e) => {
+            log::error!("Stored password hash is not valid: {}", e);
+            false
+        }
+    }
+}
+
+fn generate_session_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Evicts every session whose TTL has elapsed. Run on every `login` and on a
+/// timer by `spawn_session_reaper`, so `Sessions` can't grow without bound
+/// from users who log in once and never hit a route behind `AuthGate` again.
+fn sweep_expired_sessions(sessions: &Sessions) {
+    sessions
+        .lock()
+        .expect("sessions lock poisoned")
+        .retain(|_, session| session.issued_at.elapsed() < SESSION_TTL);
+}
+
+/// Spawns a background task that periodically sweeps `Sessions` for expired
+/// entries, independent of whether anyone logs in or hits a gated route.
+pub fn spawn_session_reaper(sessions: Sessions) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(REAP_INTERVAL).await;
+            sweep_expired_sessions(&sessions);
+        }
+    });
+}
+
+/// Registers a new account, storing only the argon2 hash of the password.
+pub async fn register(state: web::Data<AppState>, form: web::Form<Credentials>) -> HttpResponse {
+    let password_hash = hash_password(&form.password);
+
+    match sqlx::query(
+        "
+            insert into users (username, password_hash)
+            values ($1, $2)
+        ",
+    )
+    .bind(&form.username)
+    .bind(password_hash)
+    .execute(&state.get_ref().pool)
+    .await
+    {
+        Ok(_) => HttpResponse::Created().finish(),
+        Err(e) => {
+            log::warn!("Failed registering user {}: {}", form.username, e);
+            HttpResponse::Conflict().finish()
+        }
+    }
+}
+
+/// Verifies credentials and, on success, issues a session cookie backed by
+/// an in-memory token store.
+pub async fn login(state: web::Data<AppState>, form: web::Form<Credentials>) -> HttpResponse {
+    let row = sqlx::query("select password_hash from users where username = $1")
+        .bind(&form.username)
+        .fetch_optional(&state.get_ref().pool)
+        .await
+        .expect("Failed to query users");
+
+    // Always run the argon2 verify, even for an unknown username, against a
+    // fixed dummy hash so the response time doesn't leak which usernames
+    // exist.
+    let password_hash: String = match &row {
+        Some(row) => row.get("password_hash"),
+        None => DUMMY_PASSWORD_HASH.to_string(),
+    };
+    let verified = verify_password(&form.password, &password_hash);
+
+    if row.is_none() || !verified {
+        return HttpResponse::Unauthorized().finish();
+    }
+
+    sweep_expired_sessions(&state.get_ref().sessions);
+
+    let token = generate_session_token();
+    let session = Session {
+        username: form.username.clone(),
+        issued_at: Instant::now(),
+    };
+    state
+        .get_ref()
+        .sessions
+        .lock()
+        .expect("sessions lock poisoned")
+        .insert(token.clone(), session);
+
+    let mut cookie = actix_web::cookie::Cookie::new(SESSION_COOKIE, token);
+    cookie.set_http_only(true);
+    cookie.set_secure(true);
+    cookie.set_same_site(actix_web::cookie::SameSite::Lax);
+
+    HttpResponse::Ok().cookie(cookie).finish()
+}
+
+/// Middleware that rejects requests with no valid session cookie. Mount with
+/// `.wrap(AuthGate::new(sessions))` on the scopes that must stay behind a
+/// login (import routes), leaving read-only views unwrapped.
+pub struct AuthGate {
+    sessions: Sessions,
+}
+
+impl AuthGate {
+    pub fn new(sessions: Sessions) -> Self {
+        AuthGate { sessions }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for AuthGate
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = AuthGateMiddleware<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(AuthGateMiddleware {
+            service,
+            sessions: self.sessions.clone(),
+        }))
+    }
+}
+
+pub struct AuthGateMiddleware<S> {
+    service: S,
+    sessions: Sessions,
+}
+
+impl<S, B> Service<ServiceRequest> for AuthGateMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let authorized = req
+            .cookie(SESSION_COOKIE)
+            .map(|cookie| {
+                let mut sessions = self.sessions.lock().expect("sessions lock poisoned");
+                let state = sessions
+                    .get(cookie.value())
+                    .map(|session| (session.issued_at.elapsed() < SESSION_TTL, session.username.clone()));
+
+                match state {
+                    Some((true, _)) => true,
+                    Some((false, username)) => {
+                        log::info!("Session for {} expired, evicting", username);
+                        sessions.remove(cookie.value());
+                        false
+                    }
+                    None => false,
+                }
+            })
+            .unwrap_or(false);
+
+        if !authorized {
+            let (http_req, _) = req.into_parts();
+            let response = HttpResponse::Unauthorized().finish();
+            return Box::pin(async move { Ok(ServiceResponse::new(http_req, response).map_into_right_body()) });
+        }
+
+        let fut = self.service.call(req);
+        Box::pin(async move {
+            let res = fut.await?;
+            Ok(res.map_into_left_body())
+        })
+    }
+}
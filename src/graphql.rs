@@ -0,0 +1,134 @@
+use async_graphql::{Context, EmptyMutation, EmptySubscription, Object, Result, Schema, SimpleObject};
+use chrono::NaiveDate;
+use sqlx::postgres::{PgPool, PgRow};
+use sqlx::Row;
+
+/// GraphQL type mirroring the swimmer rows exposed by `swimmers_view`.
+#[derive(SimpleObject, Clone)]
+pub struct Swimmer {
+    pub id: String,
+    pub first_name: String,
+    pub last_name: String,
+    pub gender: String,
+    pub birth_date: NaiveDate,
+}
+
+#[derive(SimpleObject)]
+pub struct SwimmerTime {
+    pub swimmer: Swimmer,
+    pub style: String,
+    pub distance: i32,
+    pub course: String,
+    pub time: i32,
+    pub time_date: NaiveDate,
+}
+
+fn row_to_swimmer(row: &PgRow) -> Swimmer {
+    Swimmer {
+        id: row.get("id"),
+        first_name: row.get("name_first"),
+        last_name: row.get("name_last"),
+        gender: row.get("gender"),
+        birth_date: row.get("birth_date"),
+    }
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    /// Swimmers matching an optional gender and a case-insensitive name substring.
+    async fn swimmers(
+        &self,
+        ctx: &Context<'_>,
+        gender: Option<String>,
+        name_contains: Option<String>,
+    ) -> Result<Vec<Swimmer>> {
+        let pool = ctx.data::<PgPool>()?;
+        let pattern = format!("%{}%", name_contains.unwrap_or_default());
+
+        let swimmers = sqlx::query(
+            "
+                select id, name_first, name_last, gender, birth_date
+                from swimmer
+                where ($1::text is null or gender = $1)
+                  and (name_first ilike $2 or name_last ilike $2)
+                order by name_first, name_last
+            ",
+        )
+        .bind(gender)
+        .bind(pattern)
+        .map(|row: PgRow| row_to_swimmer(&row))
+        .fetch_all(pool)
+        .await?;
+
+        Ok(swimmers)
+    }
+
+    /// A single swimmer by id, or `None` if it doesn't exist.
+    async fn swimmer(&self, ctx: &Context<'_>, id: String) -> Result<Option<Swimmer>> {
+        let pool = ctx.data::<PgPool>()?;
+
+        let swimmer = sqlx::query(
+            "
+                select id, name_first, name_last, gender, birth_date
+                from swimmer
+                where id = $1
+            ",
+        )
+        .bind(id)
+        .map(|row: PgRow| row_to_swimmer(&row))
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(swimmer)
+    }
+
+    /// Recorded times for a swimmer, optionally filtered by style and course.
+    async fn times(
+        &self,
+        ctx: &Context<'_>,
+        swimmer_id: String,
+        style: Option<String>,
+        course: Option<String>,
+    ) -> Result<Vec<SwimmerTime>> {
+        let pool = ctx.data::<PgPool>()?;
+
+        let times = sqlx::query(
+            "
+                select s.id, s.name_first, s.name_last, s.gender, s.birth_date,
+                       t.style, t.distance, t.course, t.time_official, t.time_date
+                from swimmer_time t
+                join swimmer s on s.id = t.swimmer
+                where t.swimmer = $1
+                  and ($2::text is null or t.style = $2)
+                  and ($3::text is null or t.course = $3)
+                order by t.time_date desc
+            ",
+        )
+        .bind(swimmer_id)
+        .bind(style)
+        .bind(course)
+        .map(|row: PgRow| SwimmerTime {
+            swimmer: row_to_swimmer(&row),
+            style: row.get("style"),
+            distance: row.get("distance"),
+            course: row.get("course"),
+            time: row.get("time_official"),
+            time_date: row.get("time_date"),
+        })
+        .fetch_all(pool)
+        .await?;
+
+        Ok(times)
+    }
+}
+
+pub type CoachSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// Builds the GraphQL schema, wiring the existing connection pool into resolver context.
+pub fn build_schema(pool: PgPool) -> CoachSchema {
+    Schema::build(Query, EmptyMutation, EmptySubscription)
+        .data(pool)
+        .finish()
+}
@@ -0,0 +1,165 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde::Serialize;
+use sqlx::postgres::PgPool;
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio::time::{self, Instant};
+
+use crate::{
+    insert_swimmers_batch, insert_times_batch, register_load, ImportProgressEvent, ParsedTime,
+    Swimmer,
+};
+
+/// How long a meet's buffered batch waits for more uploads before it flushes,
+/// so a handful of re-uploads in quick succession coalesce into one write.
+const COALESCE_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Capacity of the channel feeding the worker; one slot per in-flight upload
+/// is plenty since the worker drains it continuously.
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportJobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+pub type JobStatuses = Arc<Mutex<HashMap<String, ImportJobStatus>>>;
+
+/// A parsed meet-entries upload, handed off to the worker instead of being
+/// written to Postgres inline.
+pub struct ImportBatch {
+    pub meet_id: String,
+    pub swimmers: Vec<Swimmer>,
+    pub times: Vec<ParsedTime>,
+    pub swimmer_ids: HashSet<String>,
+    pub num_entries: i32,
+}
+
+/// The buffered, not-yet-flushed state for a single meet.
+struct PendingFlush {
+    swimmers: Vec<Swimmer>,
+    times: Vec<ParsedTime>,
+    swimmer_ids: HashSet<String>,
+    num_entries: i32,
+    flush_at: Instant,
+}
+
+impl PendingFlush {
+    fn merge(&mut self, batch: ImportBatch) {
+        self.swimmers.extend(batch.swimmers);
+        self.times.extend(batch.times);
+        self.swimmer_ids.extend(batch.swimmer_ids);
+        self.num_entries += batch.num_entries;
+        self.flush_at = Instant::now() + COALESCE_WINDOW;
+    }
+}
+
+/// Spawns the background worker and returns the sender upload handlers use to
+/// submit parsed batches. The worker buffers batches per meet, merging
+/// re-uploads within `COALESCE_WINDOW` of each other, and flushes the
+/// earliest-due meet once its window elapses.
+pub fn spawn(
+    pool: PgPool,
+    jobs: JobStatuses,
+    progress_tx: broadcast::Sender<ImportProgressEvent>,
+) -> mpsc::Sender<ImportBatch> {
+    let (tx, mut rx) = mpsc::channel::<ImportBatch>(CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut pending: HashMap<String, PendingFlush> = HashMap::new();
+
+        loop {
+            let next_due = pending.values().map(|p| p.flush_at).min();
+            let sleep_until_due = async {
+                match next_due {
+                    Some(at) => time::sleep_until(at).await,
+                    None => std::future::pending::<()>().await,
+                }
+            };
+
+            tokio::select! {
+                batch = rx.recv() => {
+                    let Some(batch) = batch else {
+                        break;
+                    };
+                    let meet_id = batch.meet_id.clone();
+
+                    pending
+                        .entry(meet_id.clone())
+                        .or_insert_with(|| PendingFlush {
+                            swimmers: Vec::new(),
+                            times: Vec::new(),
+                            swimmer_ids: HashSet::new(),
+                            num_entries: 0,
+                            flush_at: Instant::now() + COALESCE_WINDOW,
+                        })
+                        .merge(batch);
+
+                    jobs.lock().await.insert(meet_id, ImportJobStatus::Queued);
+                }
+                _ = sleep_until_due, if next_due.is_some() => {
+                    let due_meet_id = pending
+                        .iter()
+                        .min_by_key(|(_, pending)| pending.flush_at)
+                        .map(|(meet_id, _)| meet_id.clone());
+
+                    if let Some(meet_id) = due_meet_id {
+                        if let Some(flush) = pending.remove(&meet_id) {
+                            jobs.lock().await.insert(meet_id.clone(), ImportJobStatus::Running);
+
+                            let status = match flush_meet(&pool, &progress_tx, &meet_id, flush).await {
+                                Ok(()) => ImportJobStatus::Done,
+                                Err(e) => {
+                                    log::error!("Failed flushing meet entries for {}: {}", meet_id, e);
+                                    ImportJobStatus::Failed
+                                }
+                            };
+
+                            jobs.lock().await.insert(meet_id, status);
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    tx
+}
+
+/// Writes a meet's coalesced batch to Postgres in a single transaction and
+/// records the load, mirroring what the old inline import did per upload.
+/// Insert and commit failures are returned instead of panicking, so a bad batch
+/// for one meet doesn't take down the worker task for every other meet; a
+/// failure inside `register_load` still panics and ends the worker task.
+async fn flush_meet(
+    pool: &PgPool,
+    progress_tx: &broadcast::Sender<ImportProgressEvent>,
+    meet_id: &str,
+    flush: PendingFlush,
+) -> Result<(), sqlx::Error> {
+    let started = Instant::now();
+
+    let mut tx = pool.begin().await?;
+    insert_swimmers_batch(&mut tx, &flush.swimmers).await?;
+    insert_times_batch(&mut tx, &flush.times).await?;
+    tx.commit().await?;
+
+    let elapsed = started.elapsed();
+    let num_swimmers = flush.swimmer_ids.len() as i32;
+    let num_entries = flush.num_entries;
+    register_load(pool, flush.swimmer_ids, num_entries, elapsed).await;
+
+    let _ = progress_tx.send(ImportProgressEvent::Done {
+        job_id: meet_id.to_string(),
+        num_swimmers,
+        num_entries,
+        duration_ms: elapsed.as_millis() as i32,
+    });
+
+    Ok(())
+}
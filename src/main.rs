@@ -1,25 +1,38 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::collections::HashSet;
+mod auth;
+mod graphql;
+mod import_worker;
+
+use std::collections::{HashMap, HashSet};
 use std::io::{self, Read};
 use std::str::from_utf8_unchecked;
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use actix_files as fs;
 use actix_multipart::form::tempfile::TempFile;
+use actix_multipart::form::text::Text;
 use actix_multipart::form::MultipartForm;
 use actix_web::middleware::Logger;
 use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use async_graphql::http::{playground_source, GraphQLPlaygroundConfig};
+use async_graphql_actix_web::{GraphQLRequest, GraphQLResponse};
 use chrono::{NaiveDate, ParseError};
 use coach::config::load_config;
 use env_logger::Env;
+use futures::StreamExt;
+use graphql::CoachSchema;
+use import_worker::{ImportBatch, ImportJobStatus, JobStatuses};
 use regex::Regex;
 use scraper::{Html, Selector};
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::{PgPool, PgRow};
-use sqlx::Row;
+use sqlx::{QueryBuilder, Row};
 use tera::{Context, Tera};
+use tokio::sync::{broadcast, mpsc, Mutex};
+use tokio_stream::wrappers::BroadcastStream;
 
 lazy_static! {
     pub static ref TEMPLATES: Tera = {
@@ -37,12 +50,58 @@ lazy_static! {
 
 struct AppState {
     pool: PgPool,
+    progress_tx: broadcast::Sender<ImportProgressEvent>,
+    import_tx: mpsc::Sender<ImportBatch>,
+    jobs: JobStatuses,
+    sessions: auth::Sessions,
+}
+
+/// Progress notifications broadcast over `/meet/entries/progress` while an
+/// import is running. Every variant carries the `job_id` (the meet id) it
+/// belongs to, so a client polling one job can filter out every other
+/// concurrent import sharing the same broadcast channel.
+#[derive(Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum ImportProgressEvent {
+    Progress {
+        job_id: String,
+        line: usize,
+        num_swimmers_seen: i32,
+        num_entries: i32,
+        elapsed_ms: u128,
+    },
+    SwimmerFailed {
+        job_id: String,
+        line: usize,
+        error: String,
+    },
+    Done {
+        job_id: String,
+        num_swimmers: i32,
+        num_entries: i32,
+        duration_ms: i32,
+    },
+}
+
+impl ImportProgressEvent {
+    fn job_id(&self) -> &str {
+        match self {
+            ImportProgressEvent::Progress { job_id, .. } => job_id,
+            ImportProgressEvent::SwimmerFailed { job_id, .. } => job_id,
+            ImportProgressEvent::Done { job_id, .. } => job_id,
+        }
+    }
 }
 
+/// How often (in rows) `import_meet_entries` emits a `Progress` event.
+const PROGRESS_EVERY: usize = 25;
+
 #[derive(Debug, MultipartForm)]
 struct MeetEntriesUploadForm {
     #[multipart(rename = "meet-entries-file")]
     files: Vec<TempFile>,
+    #[multipart(rename = "meet-id")]
+    meet_id: Text<String>,
 }
 
 #[derive(MultipartForm)]
@@ -57,7 +116,7 @@ struct MeetForm {
 }
 
 #[derive(serde::Serialize, Clone)]
-struct Swimmer {
+pub(crate) struct Swimmer {
     id: String,
     first_name: String,
     last_name: String,
@@ -91,6 +150,16 @@ async fn meets_view() -> impl Responder {
         .body(TEMPLATES.render("meets.html", &context).unwrap())
 }
 
+async fn graphql_handler(schema: web::Data<CoachSchema>, req: GraphQLRequest) -> GraphQLResponse {
+    schema.execute(req.into_inner()).await.into()
+}
+
+async fn graphql_playground() -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(playground_source(GraphQLPlaygroundConfig::new("/graphql")))
+}
+
 async fn swimmers_view(state: web::Data<AppState>) -> impl Responder {
     let swimmers = sqlx::query(
         "
@@ -118,55 +187,172 @@ async fn swimmers_view(state: web::Data<AppState>) -> impl Responder {
         .body(TEMPLATES.render("swimmers.html", &context).unwrap())
 }
 
+/// Streams `ImportProgressEvent`s for a single `job_id` as `text/event-stream`,
+/// filtering out every other concurrent import sharing the broadcast channel
+/// so the browser only sees progress for the job it's polling.
+async fn import_progress_stream(
+    state: web::Data<AppState>,
+    job_id: web::Path<String>,
+) -> impl Responder {
+    let job_id = job_id.into_inner();
+    let receiver = state.progress_tx.subscribe();
+    let stream = BroadcastStream::new(receiver).filter_map(move |event| {
+        let job_id = job_id.clone();
+        async move {
+            let event = event.ok()?;
+            if event.job_id() != job_id {
+                return None;
+            }
+            let payload = serde_json::to_string(&event).unwrap_or_default();
+            Some(Ok::<_, actix_web::Error>(web::Bytes::from(format!(
+                "data: {}\n\n",
+                payload
+            ))))
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .streaming(stream)
+}
+
+/// A parsed best-time row, ready for a batched insert.
+pub(crate) struct ParsedTime {
+    swimmer_id: String,
+    style: String,
+    distance: i32,
+    course: String,
+    time_msecs: i32,
+    time_date: NaiveDate,
+}
+
+/// How many rows go into a single multi-row `INSERT` statement.
+const BATCH_SIZE: usize = 500;
+
+/// Accepts meet entry uploads, parses them off the executor, and hands the
+/// parsed batches to the background import worker instead of writing them
+/// inline. Returns the job id for polling via `GET /meet/jobs/{id}`.
 async fn import_meet_entries(
     state: web::Data<AppState>,
     MultipartForm(form): MultipartForm<MeetEntriesUploadForm>,
 ) -> impl Responder {
+    let meet_id = form.meet_id.into_inner();
+
     for csv_file in form.files {
-        let now = Instant::now();
-        let reader = io::BufReader::new(csv_file.file);
-        let mut csv_reader = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(reader);
-
-        log::info!("Started importing meet entries.");
-        let mut swimmers = HashSet::new();
-        let mut num_entries = 0;
-        for (i, record) in csv_reader.records().enumerate() {
-            match record {
-                Ok(row) => {
-                    match import_swimmer(&state.get_ref().pool, &row, i).await {
-                        Ok(swimmer_id) => {
-                            let _b = swimmers.insert(swimmer_id);
-                        }
-                        Err(e) => log::warn!("Failed importing swimmer at line {}: {}", i + 1, e),
-                    };
-                    import_times(&state.get_ref().pool, &row, i).await;
-                    num_entries += 1;
+        log::info!("Queuing meet entries for meet {}.", meet_id);
+
+        let progress_tx = state.progress_tx.clone();
+        let job_id = meet_id.clone();
+        let (swimmers, times, swimmer_ids, num_entries) = match web::block(move || {
+            parse_meet_entries_csv(csv_file.file, job_id, progress_tx)
+        })
+        .await
+        {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::error!("Failed to parse meet entries file: {}", e);
+                continue;
+            }
+        };
+
+        state
+            .jobs
+            .lock()
+            .await
+            .insert(meet_id.clone(), ImportJobStatus::Queued);
+
+        let batch = ImportBatch {
+            meet_id: meet_id.clone(),
+            swimmers,
+            times,
+            swimmer_ids,
+            num_entries,
+        };
+
+        if state.import_tx.send(batch).await.is_err() {
+            log::error!("Import worker is gone, dropping batch for meet {}", meet_id);
+        }
+    }
+
+    HttpResponse::Accepted().json(serde_json::json!({ "job_id": meet_id }))
+}
+
+/// Reports the buffered/running/done status of a meet import job queued by
+/// `import_meet_entries`.
+async fn import_job_status(state: web::Data<AppState>, job_id: web::Path<String>) -> impl Responder {
+    let job_id = job_id.into_inner();
+    let jobs = state.jobs.lock().await;
+
+    match jobs.get(&job_id) {
+        Some(status) => HttpResponse::Ok().json(serde_json::json!({
+            "job_id": job_id,
+            "status": status,
+        })),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+/// Parses the whole CSV body on a blocking thread, collecting validated
+/// swimmer and time rows instead of inserting one row at a time. Runs inside
+/// `web::block` so the synchronous `csv` reader doesn't starve the executor.
+fn parse_meet_entries_csv<R: Read + Send + 'static>(
+    reader: R,
+    job_id: String,
+    progress_tx: broadcast::Sender<ImportProgressEvent>,
+) -> (Vec<Swimmer>, Vec<ParsedTime>, HashSet<String>, i32) {
+    let buffered = io::BufReader::new(reader);
+    let mut csv_reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(buffered);
+
+    let now = Instant::now();
+    let mut swimmers = Vec::new();
+    let mut times = Vec::new();
+    let mut swimmer_ids = HashSet::new();
+    let mut num_entries = 0;
+
+    for (i, record) in csv_reader.records().enumerate() {
+        match record {
+            Ok(row) => {
+                match parse_swimmer(&row, i) {
+                    Ok(swimmer) => {
+                        swimmer_ids.insert(swimmer.id.clone());
+                        swimmers.push(swimmer);
+                    }
+                    Err(e) => {
+                        log::warn!("Failed importing swimmer at line {}: {}", i + 1, e);
+                        let _ = progress_tx.send(ImportProgressEvent::SwimmerFailed {
+                            job_id: job_id.clone(),
+                            line: i + 1,
+                            error: e.to_string(),
+                        });
+                    }
+                };
+                times.extend(parse_times(&row, i));
+                num_entries += 1;
+
+                if (i + 1) % PROGRESS_EVERY == 0 {
+                    let _ = progress_tx.send(ImportProgressEvent::Progress {
+                        job_id: job_id.clone(),
+                        line: i + 1,
+                        num_swimmers_seen: swimmer_ids.len() as i32,
+                        num_entries,
+                        elapsed_ms: now.elapsed().as_millis(),
+                    });
                 }
-                Err(e) => log::error!("Error: {}", e),
             }
+            Err(e) => log::error!("Error: {}", e),
         }
-        let elapsed = now.elapsed();
-        register_load(&state.get_ref().pool, swimmers, num_entries, elapsed).await;
-        log::info!("Finished importing meet entries.")
     }
 
-    let context = Context::new();
-    HttpResponse::Ok()
-        .content_type("text/html; charset=utf-8")
-        .body(TEMPLATES.render("meet.html", &context).unwrap())
+    (swimmers, times, swimmer_ids, num_entries)
 }
 
-async fn import_swimmer(
-    conn: &PgPool,
-    row: &csv::StringRecord,
-    row_num: usize,
-) -> Result<String, ParseError> {
+fn parse_swimmer(row: &csv::StringRecord, row_num: usize) -> Result<Swimmer, ParseError> {
     let swimmer_id = row.get(0).unwrap().trim();
     let full_name = row.get(4).unwrap();
-    let last_name = full_name.split(' ').next();
-    let first_name = full_name.split(' ').last();
+    let last_name = full_name.split(' ').next().unwrap_or_default();
+    let first_name = full_name.split(' ').last().unwrap_or_default();
     let gender = row.get(5).unwrap().to_uppercase();
     let birth = row.get(7).unwrap();
     let birth_date = match NaiveDate::parse_from_str(birth, "%b-%d-%y") {
@@ -181,133 +367,116 @@ async fn import_swimmer(
         }
     };
 
-    sqlx::query(
-        "
-            insert into swimmer (id, name_first, name_last, gender, birth_date) 
-            values ($1, $2, $3, $4, $5)
-            on conflict do nothing
-        ",
-    )
-    .bind(swimmer_id)
-    .bind(first_name)
-    .bind(last_name)
-    .bind(gender)
-    .bind(birth_date)
-    .execute(conn)
-    .await
-    .expect("Error inserting a swimmer");
-
-    Ok(swimmer_id.to_string())
+    Ok(Swimmer {
+        id: swimmer_id.to_string(),
+        first_name: first_name.to_string(),
+        last_name: last_name.to_string(),
+        gender,
+        birth_date,
+    })
 }
 
-async fn import_times(conn: &PgPool, row: &csv::StringRecord, row_num: usize) {
-    let swimmer_id = row.get(0).unwrap();
+fn parse_times(row: &csv::StringRecord, row_num: usize) -> Vec<ParsedTime> {
+    let swimmer_id = row.get(0).unwrap().to_string();
     let event = row.get(9).unwrap();
     let distance: i32 = event.split(' ').next().unwrap().parse().unwrap();
-    let style = convert_style(event.split(' ').last().unwrap());
+    let style = convert_style(event.split(' ').last().unwrap()).to_string();
+
+    let mut times = Vec::new();
 
     let best_time_short = match row.get(12) {
-        Some(time) => {
-            if time.is_empty() {
-                ""
-            } else {
-                &time[..8]
-            }
-        }
-        None => return,
+        Some(time) if !time.is_empty() => &time[..8],
+        _ => "",
     };
 
     if !best_time_short.is_empty() {
-        let best_time_short_date = match NaiveDate::parse_from_str(row.get(13).unwrap(), "%b-%d-%y")
-        {
-            Ok(dt) => dt,
-            Err(e) => {
-                log::warn!(
-                    "Failed decoding best time date at line {}: {}",
-                    row_num + 1,
-                    e
-                );
-                return;
-            }
-        };
+        match NaiveDate::parse_from_str(row.get(13).unwrap(), "%b-%d-%y") {
+            Ok(time_date) => times.push(ParsedTime {
+                swimmer_id: swimmer_id.clone(),
+                style: style.clone(),
+                distance,
+                course: "SHORT".to_string(),
+                time_msecs: time_to_miliseconds(best_time_short),
+                time_date,
+            }),
+            Err(e) => log::warn!(
+                "Failed decoding best time date at line {}: {}",
+                row_num + 1,
+                e
+            ),
+        }
+    }
+
+    let best_time_long = match row.get(14) {
+        Some(time) if !time.is_empty() => &time[..8],
+        _ => return times,
+    };
 
-        import_time(
-            conn,
+    match NaiveDate::parse_from_str(row.get(15).unwrap(), "%b-%d-%y") {
+        Ok(time_date) => times.push(ParsedTime {
             swimmer_id,
             style,
             distance,
-            "SHORT",
-            best_time_short,
-            best_time_short_date,
-        )
-        .await;
+            course: "LONG".to_string(),
+            time_msecs: time_to_miliseconds(best_time_long),
+            time_date,
+        }),
+        Err(e) => log::warn!(
+            "Failed decoding best time date at line {}: {}",
+            row_num + 1,
+            e
+        ),
     }
 
-    let best_time_long = match row.get(14) {
-        Some(time) => {
-            if time.is_empty() {
-                return;
-            } else {
-                &time[..8]
-            }
-        }
-        None => return,
-    };
+    times
+}
 
-    let best_time_long_date = match NaiveDate::parse_from_str(row.get(15).unwrap(), "%b-%d-%y") {
-        Ok(dt) => dt,
-        Err(e) => {
-            log::warn!(
-                "Failed decoding best time date at line {}: {}",
-                row_num + 1,
-                e
-            );
-            return;
-        }
-    };
+pub(crate) async fn insert_swimmers_batch(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    swimmers: &[Swimmer],
+) -> Result<(), sqlx::Error> {
+    for chunk in swimmers.chunks(BATCH_SIZE) {
+        let mut builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::new(
+            "insert into swimmer (id, name_first, name_last, gender, birth_date) ",
+        );
+        builder.push_values(chunk, |mut b, swimmer| {
+            b.push_bind(&swimmer.id)
+                .push_bind(&swimmer.first_name)
+                .push_bind(&swimmer.last_name)
+                .push_bind(&swimmer.gender)
+                .push_bind(swimmer.birth_date);
+        });
+        builder.push(" on conflict do nothing");
+        builder.build().execute(&mut **tx).await?;
+    }
 
-    import_time(
-        conn,
-        swimmer_id,
-        style,
-        distance,
-        "LONG",
-        best_time_long,
-        best_time_long_date,
-    )
-    .await;
+    Ok(())
 }
 
-async fn import_time(
-    conn: &PgPool,
-    swimmer_id: &str,
-    style: &str,
-    distance: i32,
-    course: &str,
-    best_time: &str,
-    best_time_date: NaiveDate,
-) {
-    let best_time_msecs = time_to_miliseconds(best_time);
+pub(crate) async fn insert_times_batch(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    times: &[ParsedTime],
+) -> Result<(), sqlx::Error> {
+    for chunk in times.chunks(BATCH_SIZE) {
+        let mut builder: QueryBuilder<sqlx::Postgres> = QueryBuilder::new(
+            "insert into swimmer_time (swimmer, style, distance, course, time_official, time_date) ",
+        );
+        builder.push_values(chunk, |mut b, time| {
+            b.push_bind(&time.swimmer_id)
+                .push_bind(&time.style)
+                .push_bind(time.distance)
+                .push_bind(&time.course)
+                .push_bind(time.time_msecs)
+                .push_bind(time.time_date);
+        });
+        builder.push(" on conflict do nothing");
+        builder.build().execute(&mut **tx).await?;
+    }
 
-    sqlx::query(
-        "
-        insert into swimmer_time (swimmer, style, distance, course, time_official, time_date)
-        values ($1, $2, $3, $4, $5, $6)
-        on conflict do nothing
-    ",
-    )
-    .bind(swimmer_id)
-    .bind(style)
-    .bind(distance)
-    .bind(course)
-    .bind(best_time_msecs)
-    .bind(best_time_date)
-    .execute(conn)
-    .await
-    .expect("Error inserting swimmer's time");
+    Ok(())
 }
 
-async fn register_load(
+pub(crate) async fn register_load(
     conn: &PgPool,
     swimmers: HashSet<String>,
     num_entries: i32,
@@ -505,6 +674,60 @@ async fn import_meet_results(
         .body(TEMPLATES.render("results.html", &context).unwrap())
 }
 
+const INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_ELAPSED: Duration = Duration::from_secs(300);
+
+/// True for the transient I/O errors we expect while Postgres is still starting up.
+fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(io_error) => matches!(
+            io_error.kind(),
+            io::ErrorKind::ConnectionRefused
+                | io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+        ),
+        _ => false,
+    }
+}
+
+/// Connects to Postgres and runs pending migrations, retrying transient connection
+/// failures with exponential backoff and jitter. Any other error is treated as
+/// permanent and returned immediately.
+async fn connect_and_migrate(database_url: &str) -> Result<PgPool, sqlx::Error> {
+    let started = Instant::now();
+    let mut backoff = INITIAL_BACKOFF;
+
+    loop {
+        let attempt = async {
+            let pool = PgPool::connect(database_url).await?;
+            sqlx::migrate!("storage/migrations")
+                .run(&pool)
+                .await
+                .map_err(|e| match e {
+                    sqlx::migrate::MigrateError::Execute(e) => e,
+                    other => sqlx::Error::Configuration(other.into()),
+                })?;
+            Ok(pool)
+        };
+
+        match attempt.await {
+            Ok(pool) => return Ok(pool),
+            Err(e) if is_transient(&e) && started.elapsed() < MAX_ELAPSED => {
+                let jitter = Duration::from_millis(rand::random::<u64>() % 100);
+                log::warn!(
+                    "Database not reachable yet ({}), retrying in {:?}",
+                    e,
+                    backoff
+                );
+                tokio::time::sleep(backoff + jitter).await;
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 /// Converts text in the format mm:ss.ms to miliseconds.
 fn time_to_miliseconds(time: &str) -> i32 {
     if time.is_empty() {
@@ -556,16 +779,26 @@ async fn main() -> std::io::Result<()> {
     env_logger::Builder::from_env(Env::default().default_filter_or("info")).init();
     let config = load_config().expect("Failed to load config");
     let server_port = config.server_port;
-    let pool = PgPool::connect(&config.database.url)
+    let pool = connect_and_migrate(&config.database.url)
         .await
         .expect("Failed to connect to database");
 
-    sqlx::migrate!("storage/migrations")
-        .run(&pool)
-        .await
-        .expect("Failed to migrate database");
-
-    let app_state = AppState { pool };
+    let schema = graphql::build_schema(pool.clone());
+    let data_schema = web::Data::new(schema);
+
+    let (progress_tx, _) = broadcast::channel(256);
+    let jobs: JobStatuses = Arc::new(Mutex::new(HashMap::new()));
+    let import_tx = import_worker::spawn(pool.clone(), jobs.clone(), progress_tx.clone());
+    let sessions: auth::Sessions = Arc::new(std::sync::Mutex::new(HashMap::new()));
+    auth::spawn_session_reaper(sessions.clone());
+
+    let app_state = AppState {
+        pool,
+        progress_tx,
+        import_tx,
+        jobs,
+        sessions: sessions.clone(),
+    };
     let data_app_state = web::Data::new(app_state);
 
     HttpServer::new(move || {
@@ -575,9 +808,23 @@ async fn main() -> std::io::Result<()> {
             .route("/", web::get().to(home_view))
             .route("/meets", web::get().to(meets_view))
             .route("/swimmers", web::get().to(swimmers_view))
-            .route("/meet/entries", web::post().to(import_meet_entries))
-            .route("/meet/results", web::post().to(import_meet_results))
+            .route("/auth/register", web::post().to(auth::register))
+            .route("/auth/login", web::post().to(auth::login))
+            .route(
+                "/meet/entries/progress/{job_id}",
+                web::get().to(import_progress_stream),
+            )
+            .route("/meet/jobs/{id}", web::get().to(import_job_status))
+            .route("/graphql", web::post().to(graphql_handler))
+            .route("/graphql/playground", web::get().to(graphql_playground))
+            .service(
+                web::scope("")
+                    .wrap(auth::AuthGate::new(sessions.clone()))
+                    .route("/meet/entries", web::post().to(import_meet_entries))
+                    .route("/meet/results", web::post().to(import_meet_results)),
+            )
             .app_data(data_app_state.clone())
+            .app_data(data_schema.clone())
     })
     .bind(("0.0.0.0", server_port))?
     .run()